@@ -0,0 +1,150 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use tokio::sync::Notify;
+
+use crate::queue::OverflowPolicy;
+
+/// What actually happened to a pushed item, so the caller can report it.
+#[cfg_attr(test, derive(Debug, PartialEq, Eq))]
+pub(crate) enum PushOutcome {
+    Enqueued,
+    DroppedNewest,
+    DroppedOldest,
+}
+
+/// A fixed-capacity, multi-producer single-consumer queue that (unlike
+/// `tokio::sync::mpsc`) lets the producer evict the oldest entry on
+/// overflow, since a channel's `Sender` has no way to reach into what the
+/// `Receiver` already holds.
+pub(crate) struct RingQueue<T> {
+    buf: Mutex<VecDeque<T>>,
+    capacity: usize,
+    notify: Notify,
+}
+
+impl<T> RingQueue<T> {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            buf: Mutex::new(VecDeque::with_capacity(capacity.min(256))),
+            capacity,
+            notify: Notify::new(),
+        }
+    }
+
+    pub(crate) fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.buf.lock().unwrap().len()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.buf.lock().unwrap().is_empty()
+    }
+
+    pub(crate) fn push(&self, item: T, policy: OverflowPolicy) -> PushOutcome {
+        let mut buf = self.buf.lock().unwrap();
+
+        if buf.len() < self.capacity {
+            buf.push_back(item);
+            drop(buf);
+            self.notify.notify_one();
+            return PushOutcome::Enqueued;
+        }
+
+        match policy {
+            // At capacity 0 there's no front item to evict, so falling
+            // through to the eviction logic below would pop nothing and
+            // push anyway, leaving the queue stuck holding 1 item forever.
+            OverflowPolicy::DropOldest if self.capacity > 0 => {
+                buf.pop_front();
+                buf.push_back(item);
+                drop(buf);
+                self.notify.notify_one();
+                PushOutcome::DroppedOldest
+            }
+            OverflowPolicy::DropOldest | OverflowPolicy::DropNewest => PushOutcome::DroppedNewest,
+        }
+    }
+
+    pub(crate) async fn recv_many(&self, out: &mut Vec<T>, max: usize) -> usize {
+        loop {
+            {
+                let mut buf = self.buf.lock().unwrap();
+                if !buf.is_empty() {
+                    let n = max.min(buf.len());
+                    out.extend(buf.drain(..n));
+                    return n;
+                }
+            }
+            self.notify.notified().await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn push_enqueues_until_capacity() {
+        let q = RingQueue::new(2);
+
+        assert_eq!(q.push(1, OverflowPolicy::DropNewest), PushOutcome::Enqueued);
+        assert_eq!(q.push(2, OverflowPolicy::DropNewest), PushOutcome::Enqueued);
+        assert_eq!(q.len(), 2);
+    }
+
+    #[test]
+    fn drop_newest_rejects_the_incoming_item() {
+        let q = RingQueue::new(2);
+        q.push(1, OverflowPolicy::DropNewest);
+        q.push(2, OverflowPolicy::DropNewest);
+
+        assert_eq!(q.push(3, OverflowPolicy::DropNewest), PushOutcome::DroppedNewest);
+        assert_eq!(q.len(), 2);
+
+        let mut out = Vec::new();
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(q.recv_many(&mut out, 2));
+        assert_eq!(out, vec![1, 2]);
+    }
+
+    #[test]
+    fn drop_oldest_evicts_the_front_item() {
+        let q = RingQueue::new(2);
+        q.push(1, OverflowPolicy::DropOldest);
+        q.push(2, OverflowPolicy::DropOldest);
+
+        assert_eq!(q.push(3, OverflowPolicy::DropOldest), PushOutcome::DroppedOldest);
+        assert_eq!(q.len(), 2);
+
+        let mut out = Vec::new();
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(q.recv_many(&mut out, 2));
+        assert_eq!(out, vec![2, 3]);
+    }
+
+    #[test]
+    fn zero_capacity_drop_oldest_behaves_like_drop_newest() {
+        let q: RingQueue<i32> = RingQueue::new(0);
+
+        assert_eq!(q.push(1, OverflowPolicy::DropOldest), PushOutcome::DroppedNewest);
+        assert_eq!(q.len(), 0);
+    }
+
+    #[test]
+    fn recv_many_returns_an_already_queued_item() {
+        let q = RingQueue::new(4);
+        q.push(42, OverflowPolicy::DropNewest);
+
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let mut out = Vec::new();
+        let n = rt.block_on(q.recv_many(&mut out, 4));
+
+        assert_eq!(n, 1);
+        assert_eq!(out, vec![42]);
+    }
+}
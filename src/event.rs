@@ -43,5 +43,6 @@ impl<P> NewEventWithIdentity<'_, P> {
     pub fn enqueue(self) {
         let NewEvent { hog, event } = self.0;
         hog.send(event);
+        crate::metrics::event_enqueued();
     }
 }
@@ -0,0 +1,9 @@
+pub mod event;
+pub mod queue;
+
+mod bounded;
+mod metrics;
+mod retry;
+
+#[cfg(feature = "spool")]
+pub(crate) mod spool;
@@ -0,0 +1,87 @@
+//! Thin wrappers around the `metrics` facade. Every function here is a
+//! no-op unless the `metrics` feature is enabled, so call sites don't need
+//! to sprinkle `#[cfg(feature = "metrics")]` themselves.
+
+#[cfg(feature = "metrics")]
+use metrics::{counter, gauge, histogram};
+
+pub(crate) fn event_enqueued() {
+    #[cfg(feature = "metrics")]
+    counter!("sanglier_events_enqueued_total").increment(1);
+}
+
+pub(crate) fn event_dropped(reason: &'static str) {
+    #[cfg(feature = "metrics")]
+    counter!("sanglier_events_dropped_total", "reason" => reason).increment(1);
+    #[cfg(not(feature = "metrics"))]
+    let _ = reason;
+}
+
+pub(crate) fn serialize_failure() {
+    #[cfg(feature = "metrics")]
+    counter!("sanglier_serialize_failures_total").increment(1);
+}
+
+/// `status` is the HTTP status code when the attempt got a response at
+/// all (a connection error or timeout has none).
+pub(crate) fn batch_outcome(outcome: &'static str, status: Option<u16>) {
+    #[cfg(feature = "metrics")]
+    {
+        let status = status.map(|s| s.to_string()).unwrap_or_else(|| "none".to_owned());
+        counter!("sanglier_batches_total", "outcome" => outcome, "status" => status).increment(1);
+    }
+    #[cfg(not(feature = "metrics"))]
+    let _ = (outcome, status);
+}
+
+pub(crate) fn batch_size(n: usize) {
+    #[cfg(feature = "metrics")]
+    histogram!("sanglier_batch_size").record(n as f64);
+    #[cfg(not(feature = "metrics"))]
+    let _ = n;
+}
+
+pub(crate) fn post_latency(elapsed: std::time::Duration) {
+    #[cfg(feature = "metrics")]
+    histogram!("sanglier_post_latency_seconds").record(elapsed.as_secs_f64());
+    #[cfg(not(feature = "metrics"))]
+    let _ = elapsed;
+}
+
+pub(crate) fn queue_depth(depth: usize) {
+    #[cfg(feature = "metrics")]
+    gauge!("sanglier_queue_depth").set(depth as f64);
+    #[cfg(not(feature = "metrics"))]
+    let _ = depth;
+}
+
+pub(crate) fn inflight_batch_started() {
+    #[cfg(feature = "metrics")]
+    gauge!("sanglier_inflight_batches").increment(1.0);
+}
+
+pub(crate) fn inflight_batch_finished() {
+    #[cfg(feature = "metrics")]
+    gauge!("sanglier_inflight_batches").decrement(1.0);
+}
+
+/// Seeds the gauge with however many spool files were found sitting on
+/// disk at startup; `spool_file_written`/`spool_file_removed` keep it
+/// accurate from there as the backlog grows and drains during steady
+/// state.
+pub(crate) fn spooled_batches_found(n: usize) {
+    #[cfg(feature = "metrics")]
+    gauge!("sanglier_spooled_batches").set(n as f64);
+    #[cfg(not(feature = "metrics"))]
+    let _ = n;
+}
+
+pub(crate) fn spool_file_written() {
+    #[cfg(feature = "metrics")]
+    gauge!("sanglier_spooled_batches").increment(1.0);
+}
+
+pub(crate) fn spool_file_removed() {
+    #[cfg(feature = "metrics")]
+    gauge!("sanglier_spooled_batches").decrement(1.0);
+}
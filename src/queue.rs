@@ -1,8 +1,13 @@
 use std::time::Duration;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::borrow::Cow;
+#[cfg(feature = "spool")]
+use std::collections::VecDeque;
+#[cfg(feature = "spool")]
+use std::path::PathBuf;
 
-use tokio::sync::{mpsc, Semaphore};
+use tokio::sync::{mpsc, oneshot, Semaphore};
+use tokio::task::JoinSet;
 use tokio::time::sleep;
 use smallvec::SmallVec;
 use serde::Serialize;
@@ -10,13 +15,54 @@ use reqwest::header::{CONTENT_TYPE, HeaderValue};
 
 const MIME_JSON: HeaderValue = HeaderValue::from_static("application/json");
 
+use crate::bounded::{self, RingQueue};
 use crate::event::{NewEvent, Event};
+use crate::metrics;
+use crate::retry;
+#[cfg(feature = "spool")]
+use crate::spool;
+
+/// How `PostHog::send` behaves once a bounded queue (see
+/// [`PostHogBuilder::with_capacity`]) is full.
+///
+/// There's no `Block` variant: `PostHog::send`/`enqueue` are synchronous,
+/// so there's no caller that could actually await room freeing up. Add
+/// one only alongside an async enqueue path that can genuinely wait.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Reject the incoming event, leaving the queue as-is. The default.
+    #[default]
+    DropNewest,
+    /// Evict the oldest queued event to make room for the incoming one.
+    DropOldest,
+}
+
+/// `P` only needs to round-trip through the spool on disk when the
+/// `spool` feature is actually compiled in, so this bound collapses to a
+/// no-op otherwise instead of forcing every caller to implement
+/// `Deserialize`.
+#[cfg(feature = "spool")]
+pub trait MaybeDeserializeOwned: serde::de::DeserializeOwned {}
+#[cfg(feature = "spool")]
+impl<P: serde::de::DeserializeOwned> MaybeDeserializeOwned for P {}
+
+#[cfg(not(feature = "spool"))]
+pub trait MaybeDeserializeOwned {}
+#[cfg(not(feature = "spool"))]
+impl<P> MaybeDeserializeOwned for P {}
 
 pub struct PostHogBuilder {
-    tick: Duration, 
+    tick: Duration,
     api_key: String,
     base_url: String,
     user_agent: String,
+    request_timeout: Option<Duration>,
+    max_concurrency: usize,
+    backoff: retry::BackoffPolicy,
+    capacity: Option<usize>,
+    overflow_policy: OverflowPolicy,
+    #[cfg(feature = "spool")]
+    spool_dir: Option<PathBuf>,
 }
 
 impl Default for PostHogBuilder {
@@ -26,6 +72,13 @@ impl Default for PostHogBuilder {
             api_key: String::new(),
             base_url: String::new(),
             user_agent: "sanglier/0.1.0".to_owned(),
+            request_timeout: None,
+            max_concurrency: 1,
+            backoff: retry::BackoffPolicy::default(),
+            capacity: None,
+            overflow_policy: OverflowPolicy::default(),
+            #[cfg(feature = "spool")]
+            spool_dir: None,
         }
     }
 }
@@ -61,7 +114,69 @@ impl PostHogBuilder {
         self
     }
 
-    pub fn drive<P: Serialize + Send + Sync + 'static>(self) -> reqwest::Result<PostHog<P>> {
+    /// Persist each assembled batch to `dir` before it is POSTed, and
+    /// re-deliver anything left over from a previous run at startup.
+    /// Requires the `spool` feature.
+    #[cfg(feature = "spool")]
+    pub fn with_spool_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.spool_dir = Some(dir.into());
+        self
+    }
+
+    /// Caps how long a single `/batch` POST is allowed to hang before it's
+    /// treated as a failed (and retried) attempt.
+    pub fn with_request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = Some(timeout);
+        self
+    }
+
+    /// How many batches may be in flight to PostHog at once. Defaults to 1,
+    /// i.e. deliveries are serialized as before.
+    pub fn with_max_concurrency(mut self, n: usize) -> Self {
+        self.max_concurrency = n.max(1);
+        self
+    }
+
+    /// Base delay for the full-jitter exponential backoff applied to
+    /// retriable failures. Default: 500ms.
+    pub fn with_retry_base_delay(mut self, base: Duration) -> Self {
+        self.backoff.base = base;
+        self
+    }
+
+    /// Upper bound on the backoff delay between retries. Default: 30s.
+    pub fn with_retry_cap(mut self, cap: Duration) -> Self {
+        self.backoff.cap = cap;
+        self
+    }
+
+    /// How many delivery attempts, the first one included, a batch gets
+    /// while it keeps failing retriably before it is given up on.
+    /// Default: 8.
+    pub fn with_max_retry_attempts(mut self, max_attempts: u32) -> Self {
+        self.backoff.max_attempts = max_attempts;
+        self
+    }
+
+    /// Switches from the default unbounded queue to one holding at most
+    /// `capacity` events, so a burst or a wedged connection can't grow
+    /// memory without limit. What happens once it's full is governed by
+    /// [`Self::with_overflow_policy`].
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = Some(capacity);
+        self
+    }
+
+    /// Only takes effect once [`Self::with_capacity`] is set. Default:
+    /// [`OverflowPolicy::DropNewest`].
+    pub fn with_overflow_policy(mut self, policy: OverflowPolicy) -> Self {
+        self.overflow_policy = policy;
+        self
+    }
+
+    pub fn drive<P: Serialize + MaybeDeserializeOwned + Send + Sync + 'static>(
+        self,
+    ) -> reqwest::Result<PostHog<P>> {
         if self.api_key.is_empty() {
             panic!("Missing PostHog API key");
         }
@@ -70,29 +185,102 @@ impl PostHogBuilder {
             panic!("Missing PostHog base URL");
         }
 
-        let (send, recv) = mpsc::unbounded_channel();
+        let (outbox, inbox) = match self.capacity {
+            Some(capacity) => {
+                let queue = Arc::new(RingQueue::new(capacity));
+                (Outbox::Bounded(Arc::clone(&queue)), Inbox::Bounded(queue))
+            }
+            None => {
+                let (send, recv) = mpsc::unbounded_channel();
+                (Outbox::Unbounded(send), Inbox::Unbounded(recv))
+            }
+        };
 
-        let http = reqwest::ClientBuilder::default()
-            .user_agent(&self.user_agent)
-            .build()?;
+        let mut client = reqwest::ClientBuilder::default().user_agent(&self.user_agent);
+        if let Some(timeout) = self.request_timeout {
+            client = client.timeout(timeout);
+        }
+        let http = client.build()?;
 
         let wakeup = Arc::new(Semaphore::new(0));
+        let concurrency = Arc::new(Semaphore::new(self.max_concurrency));
+        let overflow_policy = self.overflow_policy;
+        let (control, control_recv) = mpsc::unbounded_channel();
+
+        let handle = tokio::spawn(runtime(self, http, inbox, wakeup.clone(), concurrency, control_recv));
 
         let ph = PostHog {
-            send: Arc::new(send),
-            wakeup: Arc::clone(&wakeup),
+            send: outbox,
+            wakeup,
+            overflow_policy,
+            control,
+            join: Arc::new(Mutex::new(Some(handle))),
         };
 
-        tokio::spawn(runtime(self, http, recv, wakeup));
-
         Ok(ph)
     }
 }
 
+/// Control-plane messages, sent alongside (not through) the event data
+/// queue so they aren't stuck behind whatever's already buffered.
+enum Control {
+    /// Process everything queued right now; reply once it's been POSTed
+    /// (or spooled).
+    Flush(oneshot::Sender<()>),
+    /// Drain the queue, wait for in-flight deliveries, then end the
+    /// background task; reply once that's done.
+    Shutdown(oneshot::Sender<()>),
+}
+
+enum Outbox<P> {
+    Unbounded(mpsc::UnboundedSender<Event<Option<P>>>),
+    Bounded(Arc<RingQueue<Event<Option<P>>>>),
+}
+
+impl<P> Clone for Outbox<P> {
+    fn clone(&self) -> Self {
+        match self {
+            Outbox::Unbounded(tx) => Outbox::Unbounded(tx.clone()),
+            Outbox::Bounded(queue) => Outbox::Bounded(Arc::clone(queue)),
+        }
+    }
+}
+
+enum Inbox<P> {
+    Unbounded(mpsc::UnboundedReceiver<Event<Option<P>>>),
+    Bounded(Arc<RingQueue<Event<Option<P>>>>),
+}
+
+impl<P> Inbox<P> {
+    fn is_empty(&self) -> bool {
+        match self {
+            Inbox::Unbounded(recv) => recv.is_empty(),
+            Inbox::Bounded(queue) => queue.is_empty(),
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Inbox::Unbounded(recv) => recv.len(),
+            Inbox::Bounded(queue) => queue.len(),
+        }
+    }
+
+    async fn recv_many(&mut self, out: &mut Vec<Event<Option<P>>>, max: usize) -> usize {
+        match self {
+            Inbox::Unbounded(recv) => recv.recv_many(out, max).await,
+            Inbox::Bounded(queue) => queue.recv_many(out, max).await,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct PostHog<P> {
-    send: Arc<mpsc::UnboundedSender<Event<Option<P>>>>,
+    send: Outbox<P>,
     wakeup: Arc<Semaphore>,
+    overflow_policy: OverflowPolicy,
+    control: mpsc::UnboundedSender<Control>,
+    join: Arc<Mutex<Option<tokio::task::JoinHandle<()>>>>,
 }
 
 impl<P> PostHog<P> {
@@ -115,49 +303,287 @@ impl<P> PostHog<P> {
         }
     }
 
+    /// Number of events currently queued, awaiting the next batch.
+    pub fn depth(&self) -> usize {
+        match &self.send {
+            Outbox::Unbounded(tx) => tx.len(),
+            Outbox::Bounded(queue) => queue.len(),
+        }
+    }
+
+    /// `None` for the default unbounded queue; `Some(n)` once
+    /// [`PostHogBuilder::with_capacity`] was set to `n`.
+    pub fn capacity(&self) -> Option<usize> {
+        match &self.send {
+            Outbox::Unbounded(_) => None,
+            Outbox::Bounded(queue) => Some(queue.capacity()),
+        }
+    }
+
     pub(crate) fn send(&self, event: Event<Option<P>>) {
-        let _ = self.send.send(event);
+        match &self.send {
+            Outbox::Unbounded(tx) => {
+                let _ = tx.send(event);
+            }
+            Outbox::Bounded(queue) => match queue.push(event, self.overflow_policy) {
+                bounded::PushOutcome::Enqueued => {}
+                bounded::PushOutcome::DroppedNewest => metrics::event_dropped("newest"),
+                bounded::PushOutcome::DroppedOldest => metrics::event_dropped("oldest"),
+            },
+        }
+    }
+
+    /// Waits until every event queued as of this call has been turned
+    /// into a batch and every in-flight delivery has ended: delivered,
+    /// dropped, or given up on after its last retry (with the `spool`
+    /// feature, an undelivered batch stays spooled on disk). Unlike
+    /// `force_process()` + a fixed sleep, this waits for those deliveries
+    /// before returning; it does not report whether they succeeded.
+    pub async fn flush(&self) {
+        let (tx, rx) = oneshot::channel();
+
+        if self.control.send(Control::Flush(tx)).is_err() {
+            return;
+        }
+
+        self.force_process();
+        let _ = rx.await;
+    }
+
+    /// Stops this handle from enqueuing further work, drains whatever is
+    /// already queued, waits for every in-flight delivery to finish, and
+    /// joins the background task. Use this instead of `force_process()`
+    /// plus an arbitrary sleep when a program is about to exit.
+    pub async fn shutdown(self) {
+        let (tx, rx) = oneshot::channel();
+
+        if self.control.send(Control::Shutdown(tx)).is_ok() {
+            self.force_process();
+            let _ = rx.await;
+        }
+
+        if let Some(handle) = self.join.lock().unwrap().take() {
+            let _ = handle.await;
+        }
     }
 }
 
-async fn runtime<P: Serialize + Send + Sync + 'static>(
-    hog: PostHogBuilder,
+#[derive(Serialize)]
+pub(crate) struct Batch<'a, P: Serialize> {
+    api_key: &'a str,
+    batch: &'a SmallVec<[EventRecord<P>; 32]>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct EventProperties<P: Serialize> {
+    #[serde(rename = "$process_person_profile", skip_serializing_if = "Clone::clone")]
+    pub(crate) process_person_profile: bool,
+    #[serde(rename = "$lib_name")]
+    pub(crate) lib_name: &'static str,
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    pub(crate) properties: Option<P>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct EventRecord<P: Serialize> {
+    pub(crate) event: Cow<'static, str>,
+    #[serde(skip_serializing_if = "str::is_empty")]
+    pub(crate) distinct_id: String,
+    pub(crate) properties: EventProperties<P>,
+    #[cfg(feature = "precise_timings")]
+    pub(crate) timestamp: String,
+}
+
+/// Serializes `events` and attempts delivery, retrying retriable
+/// failures with full-jitter exponential backoff until it succeeds or
+/// `policy.max_attempts` is exhausted. Spooled events have their file
+/// removed on a successful delivery and left on disk otherwise.
+async fn deliver_batch<P: Serialize>(
     http: reqwest::Client,
-    mut recv: mpsc::UnboundedReceiver<Event<Option<P>>>,
-    wakeup: Arc<Semaphore>,
+    endpoint: Arc<str>,
+    api_key: Arc<str>,
+    events: SmallVec<[EventRecord<P>; 32]>,
+    policy: retry::BackoffPolicy,
+    #[cfg(feature = "spool")] spool_path: Option<PathBuf>,
 ) {
-    #[derive(Serialize)]
-    struct Batch<'a, P: Serialize> {
-        api_key: &'a str,
-        batch: &'a SmallVec<[EventRecord<P>; 32]>,
-    }
+    metrics::batch_size(events.len());
+
+    let batch = Batch {
+        api_key: api_key.as_ref(),
+        batch: &events,
+    };
+
+    let serialized = match serde_json::to_string(&batch) {
+        Ok(serialized) => serialized,
+        Err(e) => {
+            eprintln!("[sanglier/posthog] failed to serialize batch events to string: {e}");
+            metrics::serialize_failure();
+            return;
+        }
+    };
 
-    #[derive(Serialize)]
-    struct EventProperties<P: Serialize> {
-        #[serde(rename = "$process_person_profile", skip_serializing_if = "Clone::clone")]
-        process_person_profile: bool,
-        #[serde(rename = "$lib_name")]
-        lib_name: &'static str,
-        #[serde(flatten, skip_serializing_if = "Option::is_none")]
-        properties: Option<P>,
-    }
+    metrics::inflight_batch_started();
+    let mut attempt: u32 = 0;
+
+    loop {
+        #[cfg(feature = "debug_logs")]
+        println!("sending the following body: {serialized}");
+
+        let started = std::time::Instant::now();
+        let result = http
+            .post(endpoint.as_ref())
+            .header(CONTENT_TYPE, MIME_JSON)
+            .body(serialized.clone())
+            .send()
+            .await;
+        metrics::post_latency(started.elapsed());
+
+        let status = match &result {
+            Ok(resp) => Some(resp.status().as_u16()),
+            Err(_) => None,
+        };
+
+        match retry::classify(&result) {
+            retry::Disposition::Success => {
+                #[cfg(feature = "debug_logs")]
+                println!("batch delivered");
+
+                metrics::batch_outcome("success", status);
+                metrics::inflight_batch_finished();
+
+                #[cfg(feature = "spool")]
+                if let Some(path) = &spool_path {
+                    spool::remove(path);
+                }
+
+                return;
+            }
+
+            retry::Disposition::Drop => {
+                match result {
+                    Ok(resp) => eprintln!(
+                        "[sanglier/posthog] dropping batch after non-retriable status {}",
+                        resp.status()
+                    ),
+                    Err(e) => eprintln!("[sanglier/posthog] dropping batch after permanent failure: {e}"),
+                }
+                metrics::batch_outcome("drop", status);
+                metrics::inflight_batch_finished();
+                return;
+            }
+
+            retry::Disposition::Retry { retry_after } => {
+                attempt += 1;
+                metrics::batch_outcome("retry", status);
+
+                if attempt >= policy.max_attempts {
+                    eprintln!("[sanglier/posthog] giving up on batch after {attempt} attempts");
+                    metrics::inflight_batch_finished();
+                    return;
+                }
+
+                if let Err(e) = &result {
+                    eprintln!("[sanglier/posthog] retrying batch after failed attempt: {e}");
+                }
 
-    #[derive(Serialize)]
-    struct EventRecord<P: Serialize> {
-        event: Cow<'static, str>,
-        #[serde(skip_serializing_if = "str::is_empty")]
-        distinct_id: String,
-        properties: EventProperties<P>,
-        #[cfg(feature = "precise_timings")]
-        timestamp: String,
+                sleep(retry::next_delay(&policy, attempt, retry_after)).await;
+            }
+        }
     }
+}
 
-    let batch_endpoint = format!("{}/batch", hog.base_url);
+/// Acquires a concurrency permit, hands `events` off to [`deliver_batch`]
+/// on its own task tracked in `inflight`, and returns immediately —
+/// callers don't wait on delivery, only on a later drain of `inflight`.
+async fn spawn_batch<P: Serialize + Send + Sync + 'static>(
+    inflight: &mut JoinSet<()>,
+    concurrency: &Arc<Semaphore>,
+    http: &reqwest::Client,
+    endpoint: &Arc<str>,
+    api_key: &Arc<str>,
+    policy: retry::BackoffPolicy,
+    events: SmallVec<[EventRecord<P>; 32]>,
+    #[cfg(feature = "spool")] spool_path: Option<PathBuf>,
+) {
+    let permit = Arc::clone(concurrency).acquire_owned().await.unwrap();
+    let http = http.clone();
+    let endpoint = Arc::clone(endpoint);
+    let api_key = Arc::clone(api_key);
+
+    inflight.spawn(async move {
+        let _permit = permit;
+        deliver_batch(
+            http,
+            endpoint,
+            api_key,
+            events,
+            policy,
+            #[cfg(feature = "spool")]
+            spool_path,
+        )
+        .await;
+    });
+}
+
+/// Awaits every task currently tracked in `inflight`, including ones
+/// spawned while this call is running.
+async fn drain_inflight(inflight: &mut JoinSet<()>) {
+    while inflight.join_next().await.is_some() {}
+}
+
+async fn runtime<P: Serialize + MaybeDeserializeOwned + Send + Sync + 'static>(
+    hog: PostHogBuilder,
+    http: reqwest::Client,
+    mut recv: Inbox<P>,
+    wakeup: Arc<Semaphore>,
+    concurrency: Arc<Semaphore>,
+    mut control: mpsc::UnboundedReceiver<Control>,
+) {
+    let batch_endpoint: Arc<str> = Arc::from(format!("{}/batch", hog.base_url));
+    let api_key: Arc<str> = Arc::from(hog.api_key.as_str());
     let mut events_serde: SmallVec<[EventRecord<P>; 32]> = smallvec::smallvec![];
     let mut events_chan = Vec::with_capacity(32);
+    let mut inflight: JoinSet<()> = JoinSet::new();
 
     const PROCESS_MAX: usize = 128;
 
+    #[cfg(feature = "spool")]
+    let leftover: VecDeque<spool::SpooledFile<P>> = match &hog.spool_dir {
+        Some(dir) => {
+            if let Err(e) = std::fs::create_dir_all(dir) {
+                eprintln!("[sanglier/posthog] failed to create spool dir {}: {e}", dir.display());
+                VecDeque::new()
+            } else {
+                match spool::scan(dir) {
+                    Ok(files) => files.into(),
+                    Err(e) => {
+                        eprintln!("[sanglier/posthog] failed to scan spool dir {}: {e}", dir.display());
+                        VecDeque::new()
+                    }
+                }
+            }
+        }
+        None => VecDeque::new(),
+    };
+
+    #[cfg(feature = "spool")]
+    metrics::spooled_batches_found(leftover.len());
+
+    #[cfg(feature = "spool")]
+    for spooled in leftover {
+        spawn_batch(
+            &mut inflight,
+            &concurrency,
+            &http,
+            &batch_endpoint,
+            &api_key,
+            hog.backoff,
+            spooled.events,
+            Some(spooled.path),
+        )
+        .await;
+    }
+
     loop {
         if recv.is_empty() {
             let wait = sleep(hog.tick);
@@ -169,6 +595,48 @@ async fn runtime<P: Serialize + Send + Sync + 'static>(
                         permit.forget();
                     }
                 }
+                ctrl = control.recv() => {
+                    match ctrl {
+                        Some(ctrl) => {
+                            if handle_control(
+                                ctrl,
+                                &mut recv,
+                                &mut events_serde,
+                                &mut events_chan,
+                                &mut inflight,
+                                &concurrency,
+                                &http,
+                                &batch_endpoint,
+                                &api_key,
+                                &hog,
+                                PROCESS_MAX,
+                            ).await {
+                                return;
+                            }
+                        }
+                        // Every `PostHog` handle was dropped without a
+                        // `shutdown()` call, so the control sender is
+                        // gone too. Nothing can ever wake this task
+                        // again: drain what's left and end it, rather
+                        // than spinning on an endlessly-ready `recv()`.
+                        None => {
+                            drain_queue(
+                                &mut recv,
+                                &mut events_serde,
+                                &mut events_chan,
+                                &mut inflight,
+                                &concurrency,
+                                &http,
+                                &batch_endpoint,
+                                &api_key,
+                                &hog,
+                                PROCESS_MAX,
+                            ).await;
+                            drain_inflight(&mut inflight).await;
+                            return;
+                        }
+                    }
+                }
             }
         }
 
@@ -176,13 +644,54 @@ async fn runtime<P: Serialize + Send + Sync + 'static>(
             permit.forget();
         }
 
+        loop {
+            match control.try_recv() {
+                Ok(ctrl) => {
+                    if handle_control(
+                        ctrl,
+                        &mut recv,
+                        &mut events_serde,
+                        &mut events_chan,
+                        &mut inflight,
+                        &concurrency,
+                        &http,
+                        &batch_endpoint,
+                        &api_key,
+                        &hog,
+                        PROCESS_MAX,
+                    ).await {
+                        return;
+                    }
+                }
+                Err(mpsc::error::TryRecvError::Empty) => break,
+                Err(mpsc::error::TryRecvError::Disconnected) => {
+                    drain_queue(
+                        &mut recv,
+                        &mut events_serde,
+                        &mut events_chan,
+                        &mut inflight,
+                        &concurrency,
+                        &http,
+                        &batch_endpoint,
+                        &api_key,
+                        &hog,
+                        PROCESS_MAX,
+                    ).await;
+                    drain_inflight(&mut inflight).await;
+                    return;
+                }
+            }
+        }
+
+        metrics::queue_depth(recv.len());
+
         let max = recv.len().min(PROCESS_MAX);
         if max == 0 {
             continue;
         }
 
         // shouldn't sleep
-        let processed = recv.recv_many(&mut events_chan, max).await;
+        recv.recv_many(&mut events_chan, max).await;
 
         for ev in events_chan.drain(..) {
             events_serde.push(EventRecord {
@@ -198,47 +707,95 @@ async fn runtime<P: Serialize + Send + Sync + 'static>(
             });
         }
 
-        let batch = Batch {
-            api_key: &hog.api_key,
-            batch: &events_serde,
-        };
-
-        match serde_json::to_string(&batch) {
-            Ok(serialized) => {
-                #[cfg(feature = "debug_logs")]
-                println!("sending the following body: {serialized}");
-
-                let result = http.post(&batch_endpoint)
-                    .header(CONTENT_TYPE, MIME_JSON)
-                    .body(serialized)
-                    .send()
-                    .await
-                    .and_then(|resp| resp.error_for_status());
+        #[cfg(feature = "spool")]
+        let spooled_path = hog.spool_dir.as_ref().and_then(|dir| {
+            spool::write_batch(dir, &events_serde)
+                .map_err(|e| eprintln!("[sanglier/posthog] failed to spool batch: {e}"))
+                .ok()
+        });
 
-                #[cfg(feature = "debug_logs")]
-                match result {
-                    Ok(resp) => {
-                        let response = resp.text().await.unwrap();
-                        println!("responded with {response}");
-                    }
+        let events = std::mem::take(&mut events_serde);
+
+        spawn_batch(
+            &mut inflight,
+            &concurrency,
+            &http,
+            &batch_endpoint,
+            &api_key,
+            hog.backoff,
+            events,
+            #[cfg(feature = "spool")]
+            spooled_path,
+        )
+        .await;
+
+        // Keep looping while there's more to pull off the queue; only a
+        // `Control::Shutdown`, or every `PostHog` handle being dropped,
+        // ends this task.
+    }
+}
 
-                    Err(e) => {
-                        eprintln!("[sanglier/posthog] failed to send batch request: {e}");
-                    }
-                }
+/// Pulls everything currently sitting in the data queue into batches,
+/// spooling/spawning each the same way the main loop would. Shared by
+/// `handle_control` (for `Flush`/`Shutdown`) and by the runtime loop's
+/// handling of a closed control channel, which must drain the same way a
+/// graceful shutdown would before ending the task.
+#[allow(clippy::too_many_arguments)]
+async fn drain_queue<P: Serialize + MaybeDeserializeOwned + Send + Sync + 'static>(
+    recv: &mut Inbox<P>,
+    events_serde: &mut SmallVec<[EventRecord<P>; 32]>,
+    events_chan: &mut Vec<Event<Option<P>>>,
+    inflight: &mut JoinSet<()>,
+    concurrency: &Arc<Semaphore>,
+    http: &reqwest::Client,
+    batch_endpoint: &Arc<str>,
+    api_key: &Arc<str>,
+    hog: &PostHogBuilder,
+    process_max: usize,
+) {
+    loop {
+        let max = recv.len().min(process_max);
+        if max == 0 {
+            break;
+        }
 
-                #[cfg(not(feature = "debug_logs"))]
-                if let Err(e) = result {
-                    eprintln!("[sanglier/posthog] failed to send batch request: {e}");
-                }
-            }
+        let processed = recv.recv_many(events_chan, max).await;
 
-            Err(e) => {
-                eprintln!("[sanglier/posthog] failed to serialize batch events to string: {e}");
-            }
+        for ev in events_chan.drain(..) {
+            events_serde.push(EventRecord {
+                event: ev.name,
+                properties: EventProperties {
+                    process_person_profile: !ev.distinct_id.is_empty(),
+                    lib_name: "sanglier",
+                    properties: ev.properties,
+                },
+                distinct_id: ev.distinct_id,
+                #[cfg(feature = "precise_timings")]
+                timestamp: ev.timestamp.format("%+").to_string(),
+            });
         }
 
-        events_serde.clear();
+        #[cfg(feature = "spool")]
+        let spooled_path = hog.spool_dir.as_ref().and_then(|dir| {
+            spool::write_batch(dir, events_serde)
+                .map_err(|e| eprintln!("[sanglier/posthog] failed to spool batch: {e}"))
+                .ok()
+        });
+
+        let events = std::mem::take(events_serde);
+
+        spawn_batch(
+            inflight,
+            concurrency,
+            http,
+            batch_endpoint,
+            api_key,
+            hog.backoff,
+            events,
+            #[cfg(feature = "spool")]
+            spooled_path,
+        )
+        .await;
 
         if processed < max {
             break;
@@ -246,13 +803,55 @@ async fn runtime<P: Serialize + Send + Sync + 'static>(
     }
 }
 
+/// Handles one control-plane message. Returns `true` once the caller
+/// should end the `runtime` task (i.e. after a `Shutdown`).
+#[allow(clippy::too_many_arguments)]
+async fn handle_control<P: Serialize + MaybeDeserializeOwned + Send + Sync + 'static>(
+    ctrl: Control,
+    recv: &mut Inbox<P>,
+    events_serde: &mut SmallVec<[EventRecord<P>; 32]>,
+    events_chan: &mut Vec<Event<Option<P>>>,
+    inflight: &mut JoinSet<()>,
+    concurrency: &Arc<Semaphore>,
+    http: &reqwest::Client,
+    batch_endpoint: &Arc<str>,
+    api_key: &Arc<str>,
+    hog: &PostHogBuilder,
+    process_max: usize,
+) -> bool {
+    drain_queue(
+        recv,
+        events_serde,
+        events_chan,
+        inflight,
+        concurrency,
+        http,
+        batch_endpoint,
+        api_key,
+        hog,
+        process_max,
+    )
+    .await;
+
+    match ctrl {
+        Control::Flush(reply) => {
+            drain_inflight(inflight).await;
+            let _ = reply.send(());
+            false
+        }
+        Control::Shutdown(reply) => {
+            drain_inflight(inflight).await;
+            let _ = reply.send(());
+            true
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use std::time::Duration;
     use std::env::var;
 
     use serde::Serialize;
-    use tokio::time::sleep;
 
     use super::*;
 
@@ -284,9 +883,8 @@ mod tests {
             })
             .enqueue();
 
-        hog.force_process();
         rt.block_on(async move {
-            sleep(Duration::from_secs(5)).await;
+            hog.flush().await;
         });
     }
 }
@@ -0,0 +1,130 @@
+use std::time::Duration;
+
+use reqwest::StatusCode;
+
+/// Retry/backoff knobs, configurable from [`crate::queue::PostHogBuilder`].
+#[derive(Clone, Copy)]
+pub(crate) struct BackoffPolicy {
+    pub(crate) base: Duration,
+    pub(crate) cap: Duration,
+    pub(crate) max_attempts: u32,
+}
+
+impl Default for BackoffPolicy {
+    fn default() -> Self {
+        Self {
+            base: Duration::from_millis(500),
+            cap: Duration::from_secs(30),
+            max_attempts: 8,
+        }
+    }
+}
+
+/// What to do with a batch after an attempted delivery.
+pub(crate) enum Disposition {
+    Success,
+    /// Worth trying again; `retry_after` is a floor taken from a
+    /// `Retry-After` response header in its delay-seconds form, if the
+    /// server sent one (an HTTP-date value is ignored).
+    Retry { retry_after: Option<Duration> },
+    /// Not worth retrying — a non-429 4xx, or a response we can't
+    /// classify as transient.
+    Drop,
+}
+
+/// Classifies a completed send attempt: retry on connection errors,
+/// timeouts, 429, and 5xx; drop permanently on any other 4xx.
+pub(crate) fn classify(result: &Result<reqwest::Response, reqwest::Error>) -> Disposition {
+    match result {
+        Ok(resp) => {
+            let status = resp.status();
+            if status.is_success() {
+                Disposition::Success
+            } else if status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error() {
+                Disposition::Retry {
+                    retry_after: retry_after(resp),
+                }
+            } else {
+                Disposition::Drop
+            }
+        }
+        Err(e) if e.is_timeout() || e.is_connect() || e.is_request() => {
+            Disposition::Retry { retry_after: None }
+        }
+        Err(_) => Disposition::Drop,
+    }
+}
+
+fn retry_after(resp: &reqwest::Response) -> Option<Duration> {
+    resp.headers()
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .parse::<u64>()
+        .ok()
+        .map(Duration::from_secs)
+}
+
+/// Full-jitter exponential backoff: `random(0, min(cap, base * 2^attempt))`,
+/// floored by `retry_after` when the server gave us one.
+pub(crate) fn next_delay(policy: &BackoffPolicy, attempt: u32, retry_after: Option<Duration>) -> Duration {
+    let exp = policy
+        .base
+        .checked_mul(1u32 << attempt.min(31))
+        .unwrap_or(policy.cap);
+    let ceiling = exp.min(policy.cap);
+
+    let jittered = Duration::from_secs_f64(fastrand::f64() * ceiling.as_secs_f64());
+
+    match retry_after {
+        Some(floor) => jittered.max(floor),
+        None => jittered,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn next_delay_never_exceeds_the_cap() {
+        let policy = BackoffPolicy {
+            base: Duration::from_millis(500),
+            cap: Duration::from_secs(30),
+            max_attempts: 8,
+        };
+
+        for attempt in 0..10 {
+            let delay = next_delay(&policy, attempt, None);
+            assert!(delay <= policy.cap, "attempt {attempt}: delay {delay:?} exceeded cap {:?}", policy.cap);
+        }
+    }
+
+    #[test]
+    fn next_delay_saturates_instead_of_overflowing_on_huge_attempts() {
+        let policy = BackoffPolicy {
+            base: Duration::from_millis(500),
+            cap: Duration::from_secs(30),
+            max_attempts: 8,
+        };
+
+        let delay = next_delay(&policy, u32::MAX, None);
+        assert!(delay <= policy.cap);
+    }
+
+    #[test]
+    fn next_delay_is_floored_by_retry_after() {
+        let policy = BackoffPolicy {
+            base: Duration::from_millis(500),
+            cap: Duration::from_secs(30),
+            max_attempts: 8,
+        };
+        let floor = Duration::from_secs(45);
+
+        // `retry_after` exceeds `cap`, so it must win over the jittered
+        // exponential value even though that value is itself bounded by
+        // the cap.
+        let delay = next_delay(&policy, 2, Some(floor));
+        assert!(delay >= floor);
+    }
+}
@@ -0,0 +1,289 @@
+use std::borrow::Cow;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::SystemTime;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use smallvec::SmallVec;
+
+use crate::metrics;
+use crate::queue::{EventProperties, EventRecord};
+
+/// Plain mirror of [`EventRecord`]/[`EventProperties`] used only for the
+/// on-disk spool. Postcard isn't self-describing, so it can't round-trip
+/// `#[serde(flatten)]` (the field has no declared length up front) or
+/// `skip_serializing_if` (omitting a field shifts every byte after it) —
+/// both of which the JSON-oriented `EventRecord` relies on. This type has
+/// neither, so encode and decode sides are split: `Ref` borrows from an
+/// existing `EventRecord` to write, `Owned` is reconstructed into one on
+/// read.
+#[derive(Serialize)]
+struct SpoolRecordRef<'a, P: Serialize> {
+    event: &'a str,
+    distinct_id: &'a str,
+    process_person_profile: bool,
+    properties: &'a Option<P>,
+    #[cfg(feature = "precise_timings")]
+    timestamp: &'a str,
+}
+
+impl<'a, P: Serialize> SpoolRecordRef<'a, P> {
+    fn from_event_record(record: &'a EventRecord<P>) -> Self {
+        Self {
+            event: record.event.as_ref(),
+            distinct_id: record.distinct_id.as_str(),
+            process_person_profile: record.properties.process_person_profile,
+            properties: &record.properties.properties,
+            #[cfg(feature = "precise_timings")]
+            timestamp: record.timestamp.as_str(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct SpoolRecordOwned<P> {
+    event: String,
+    distinct_id: String,
+    process_person_profile: bool,
+    properties: Option<P>,
+    #[cfg(feature = "precise_timings")]
+    timestamp: String,
+}
+
+impl<P: Serialize> From<SpoolRecordOwned<P>> for EventRecord<P> {
+    fn from(rec: SpoolRecordOwned<P>) -> Self {
+        EventRecord {
+            event: Cow::Owned(rec.event),
+            distinct_id: rec.distinct_id,
+            properties: EventProperties {
+                process_person_profile: rec.process_person_profile,
+                lib_name: "sanglier",
+                properties: rec.properties,
+            },
+            #[cfg(feature = "precise_timings")]
+            timestamp: rec.timestamp,
+        }
+    }
+}
+
+/// Bumped whenever the on-disk layout changes; a spool file whose header
+/// byte doesn't match the version we understand is skipped rather than
+/// mis-parsed.
+const FORMAT_VERSION: u8 = 1;
+
+static SEQ: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug)]
+pub(crate) enum SpoolError {
+    Io(io::Error),
+    Encode(postcard::Error),
+    Decode(postcard::Error),
+    UnsupportedVersion(u8),
+    Truncated,
+}
+
+impl From<io::Error> for SpoolError {
+    fn from(e: io::Error) -> Self {
+        SpoolError::Io(e)
+    }
+}
+
+impl std::fmt::Display for SpoolError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SpoolError::Io(e) => write!(f, "{e}"),
+            SpoolError::Encode(e) => write!(f, "failed to encode spooled batch: {e}"),
+            SpoolError::Decode(e) => write!(f, "failed to decode spooled batch: {e}"),
+            SpoolError::UnsupportedVersion(v) => {
+                write!(f, "spool file has unsupported format version {v}")
+            }
+            SpoolError::Truncated => write!(f, "spool file is too short to hold its header"),
+        }
+    }
+}
+
+impl std::error::Error for SpoolError {}
+
+pub(crate) struct SpooledFile<P: Serialize> {
+    pub(crate) path: PathBuf,
+    pub(crate) events: SmallVec<[EventRecord<P>; 32]>,
+}
+
+/// Serializes `events` with a small version+count header and atomically
+/// drops it into `dir`: write to a temp file, `fsync`, rename into place,
+/// then `fsync` the directory itself — without that last step, a crash
+/// right after the rename can lose the directory-entry update on most
+/// POSIX filesystems even though the file's own bytes are durable,
+/// making the spooled batch vanish on reboot.
+pub(crate) fn write_batch<P: Serialize>(
+    dir: &Path,
+    events: &SmallVec<[EventRecord<P>; 32]>,
+) -> Result<PathBuf, SpoolError> {
+    let refs: Vec<SpoolRecordRef<'_, P>> = events.iter().map(SpoolRecordRef::from_event_record).collect();
+    let mut body = postcard::to_allocvec(&refs).map_err(SpoolError::Encode)?;
+
+    let mut file_bytes = Vec::with_capacity(body.len() + 5);
+    file_bytes.push(FORMAT_VERSION);
+    file_bytes.extend_from_slice(&(events.len() as u32).to_le_bytes());
+    file_bytes.append(&mut body);
+
+    let name = unique_name();
+    let tmp_path = dir.join(format!("{name}.batch.tmp"));
+    let final_path = dir.join(format!("{name}.batch"));
+
+    let mut tmp = File::create(&tmp_path)?;
+    tmp.write_all(&file_bytes)?;
+    tmp.sync_all()?;
+    drop(tmp);
+
+    fs::rename(&tmp_path, &final_path)?;
+    File::open(dir)?.sync_all()?;
+
+    metrics::spool_file_written();
+
+    Ok(final_path)
+}
+
+/// Scans `dir` for batch files left behind by a previous run, oldest
+/// first, and decodes each one. A file this build can't make sense of
+/// (wrong version, truncated) is logged and skipped instead of aborting
+/// the whole scan.
+pub(crate) fn scan<P: Serialize + DeserializeOwned>(dir: &Path) -> io::Result<Vec<SpooledFile<P>>> {
+    let mut candidates: Vec<(PathBuf, SystemTime)> = Vec::new();
+
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some("batch") {
+            continue;
+        }
+
+        let created = entry
+            .metadata()
+            .and_then(|m| m.created().or_else(|_| m.modified()))
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+
+        candidates.push((path, created));
+    }
+
+    candidates.sort_by_key(|(_, created)| *created);
+
+    let mut loaded = Vec::with_capacity(candidates.len());
+    for (path, _) in candidates {
+        match read_batch(&path) {
+            Ok(events) => loaded.push(SpooledFile { path, events }),
+            Err(e) => {
+                eprintln!(
+                    "[sanglier/posthog] skipping unreadable spool file {}: {e}",
+                    path.display()
+                );
+            }
+        }
+    }
+
+    Ok(loaded)
+}
+
+fn read_batch<P: Serialize + DeserializeOwned>(path: &Path) -> Result<SmallVec<[EventRecord<P>; 32]>, SpoolError> {
+    let bytes = fs::read(path)?;
+
+    let version = *bytes.first().ok_or(SpoolError::Truncated)?;
+    if version != FORMAT_VERSION {
+        return Err(SpoolError::UnsupportedVersion(version));
+    }
+
+    // The event count in the header is informational for now; postcard
+    // recovers the element count from the encoded body itself.
+    let body = bytes.get(5..).ok_or(SpoolError::Truncated)?;
+
+    let records: Vec<SpoolRecordOwned<P>> = postcard::from_bytes(body).map_err(SpoolError::Decode)?;
+    Ok(records.into_iter().map(EventRecord::from).collect())
+}
+
+/// Deletes a spool file once its batch has been delivered.
+pub(crate) fn remove(path: &Path) {
+    match fs::remove_file(path) {
+        Ok(()) => metrics::spool_file_removed(),
+        Err(e) => eprintln!(
+            "[sanglier/posthog] failed to remove delivered spool file {}: {e}",
+            path.display()
+        ),
+    }
+}
+
+fn unique_name() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    let seq = SEQ.fetch_add(1, Ordering::Relaxed);
+    format!("{nanos}-{seq}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Props {
+        foo: String,
+    }
+
+    fn record(distinct_id: &str, properties: Option<Props>) -> EventRecord<Props> {
+        EventRecord {
+            event: Cow::Borrowed("test_event"),
+            distinct_id: distinct_id.to_owned(),
+            properties: EventProperties {
+                process_person_profile: !distinct_id.is_empty(),
+                lib_name: "sanglier",
+                properties,
+            },
+            #[cfg(feature = "precise_timings")]
+            timestamp: "2024-01-01T00:00:00Z".to_owned(),
+        }
+    }
+
+    /// Guards against the flatten/skip_serializing_if traps that made
+    /// every real batch fail to spool: an anonymous event (empty
+    /// `distinct_id`, the `skip_serializing_if` case) and an identified
+    /// one with real properties (the `flatten` case) must both survive a
+    /// `write_batch` -> `scan` round trip intact and in order.
+    #[test]
+    fn write_batch_round_trips_through_scan() {
+        let dir = std::env::temp_dir().join(format!("sanglier-spool-test-{}", unique_name()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let events: SmallVec<[EventRecord<Props>; 32]> = smallvec::smallvec![
+            record("", None),
+            record(
+                "user-123",
+                Some(Props {
+                    foo: "bar".to_owned()
+                })
+            ),
+        ];
+
+        write_batch(&dir, &events).unwrap();
+
+        let mut loaded = scan::<Props>(&dir).unwrap();
+        assert_eq!(loaded.len(), 1);
+        let spooled = loaded.remove(0);
+
+        assert_eq!(spooled.events.len(), 2);
+        assert_eq!(spooled.events[0].event.as_ref(), "test_event");
+        assert_eq!(spooled.events[0].distinct_id, "");
+        assert_eq!(spooled.events[0].properties.properties, None);
+        assert_eq!(spooled.events[1].distinct_id, "user-123");
+        assert_eq!(
+            spooled.events[1].properties.properties,
+            Some(Props {
+                foo: "bar".to_owned()
+            })
+        );
+
+        fs::remove_dir_all(&dir).ok();
+    }
+}